@@ -1,167 +1,313 @@
-use std::ops::{AddAssign, MulAssign, DivAssign};
-use std::str::FromStr;
-use rom::Rom;
+use std::collections::{HashMap, HashSet};
+use std::ops::{AddAssign, SubAssign, MulAssign, DivAssign};
+use fault::Fault;
+use rom::{Instruction, Operand, Rom};
 
 #[derive(Debug)]
 pub struct Register {
-    val: u64,
+    val: i64,
 }
 
 impl Register {
     fn new() -> Register {
         Register { val: 0 }
     }
-    
-    fn is_odd(&self) -> bool {
+
+    fn is_even(&self) -> bool {
         self.val % 2 == 0
     }
 }
 
-impl AddAssign<u64> for Register {
-    fn add_assign(&mut self, _rhs: u64) {
+impl AddAssign<i64> for Register {
+    fn add_assign(&mut self, _rhs: i64) {
         self.val += _rhs;
     }
 }
 
-impl MulAssign<u64> for Register {
-    fn mul_assign(&mut self, _rhs: u64) {
+impl SubAssign<i64> for Register {
+    fn sub_assign(&mut self, _rhs: i64) {
+        self.val -= _rhs;
+    }
+}
+
+impl MulAssign<i64> for Register {
+    fn mul_assign(&mut self, _rhs: i64) {
         self.val *= _rhs;
     }
 }
 
-impl DivAssign<u64> for Register {
-    fn div_assign(&mut self, _rhs: u64) {
+impl DivAssign<i64> for Register {
+    fn div_assign(&mut self, _rhs: i64) {
         self.val /= _rhs;
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum RegisterId {
     A,
     B,
+    P,
+}
+
+/// Size of the addressable `ld`/`st` memory, in cells.
+const MEMORY_SIZE: usize = 256;
+
+/// Maximum depth of the `push`/`pop`/`call`/`ret` stack, in cells.
+const STACK_SIZE: usize = 256;
+
+/// The execution state of a `Cpu`.
+#[derive(Debug, PartialEq)]
+pub enum State {
+    Init,
+    Running,
+    Halted,
+}
+
+/// Why a `Cpu::run()` stopped.
+#[derive(Debug, PartialEq)]
+pub enum Exit {
+    /// The program counter ran off the end of the ROM.
+    Halted,
+    /// The program counter and registers together reached a state already executed before.
+    LoopDetected { at: usize },
+    /// Execution hit a runtime fault.
+    Faulted(Fault),
+}
+
+/// Per-opcode and per-PC execution counts, collected only while profiling is enabled.
+struct Profile {
+    opcode_counts: HashMap<&'static str, usize>,
+    pc_counts: HashMap<usize, usize>,
+}
+
+impl Profile {
+    fn new() -> Profile {
+        Profile { opcode_counts: HashMap::new(), pc_counts: HashMap::new() }
+    }
+
+    fn record(&mut self, opcode: &'static str, pc: usize) {
+        *self.opcode_counts.entry(opcode).or_insert(0) += 1;
+        *self.pc_counts.entry(pc).or_insert(0) += 1;
+    }
+}
+
+/// Summary produced by `Cpu::profile_report()`.
+#[derive(Debug, PartialEq)]
+pub struct ProfileReport {
+    /// Executed opcode names, most-executed first.
+    pub opcode_counts: Vec<(&'static str, usize)>,
+    /// The `top_n` hottest PCs, most-executed first.
+    pub hot_pcs: Vec<(usize, usize)>,
 }
 
 pub struct Cpu {
     a: Register,
     b: Register,
+    p: Register,
     pc: usize,
     rom: Rom,
+    state: State,
+    memory: Vec<i64>,
+    stack: Vec<i64>,
+    profile: Option<Profile>,
 }
 
 impl Cpu {
     pub fn new(rom: Rom) -> Cpu {
+        Cpu::with_program_id(rom, 0)
+    }
+
+    /// Create a `Cpu` with register `p` seeded to `id`, for dual-CPU (duet) mode.
+    pub fn with_program_id(rom: Rom, id: i64) -> Cpu {
         Cpu {
             a: Register::new(),
             b: Register::new(),
+            p: Register { val: id },
             pc: 0,
             rom: rom,
+            state: State::Init,
+            memory: vec![0; MEMORY_SIZE],
+            stack: Vec::new(),
+            profile: None,
         }
     }
 
-    /// Execute the next instruction
-    pub fn step(&mut self) {
-        let data = self.rom.get(self.pc);
-        self.exec(data);
-    }
-    
-    /// Parse raw data into instruction
-    fn exec(&mut self, data: String) {
-        let tokens: Vec<&str> = data.split(' ').collect();
-        let (opcode, args) = tokens.split_at(1);
-        
-        println!("{}", data);
-        match opcode[0] {
-           "hlf"  => self.read_hlf(args),
-           "inc" => self.read_inc(args),
-           //"jie" => self.read_jie(args),
-           "jio" => self.read_jio(args),
-           "jmp" => self.read_jmp(args),
-           "tpl" => self.read_tpl(args),
-            _ => panic!("unimplemented or illegal instruction: {}", data),
+    /// Current depth of the call/push stack.
+    pub fn sp(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Current program counter.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Start accumulating per-opcode and per-PC execution counts.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::new());
+    }
+
+    /// Summarize the counts gathered since `enable_profiling()`, or `None` if
+    /// profiling was never turned on.
+    pub fn profile_report(&self, top_n: usize) -> Option<ProfileReport> {
+        let profile = match self.profile {
+            Some(ref profile) => profile,
+            None => return None,
         };
+
+        let mut opcode_counts: Vec<(&'static str, usize)> =
+            profile.opcode_counts.iter().map(|(&op, &count)| (op, count)).collect();
+        opcode_counts.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let mut hot_pcs: Vec<(usize, usize)> =
+            profile.pc_counts.iter().map(|(&pc, &count)| (pc, count)).collect();
+        hot_pcs.sort_by(|a, b| b.1.cmp(&a.1));
+        hot_pcs.truncate(top_n);
+
+        Some(ProfileReport { opcode_counts: opcode_counts, hot_pcs: hot_pcs })
     }
-    
-    /// Parse hlf instruction
-    fn read_hlf(&mut self, args: &[&str]) {
-        if args.len() != 1 {
-            panic!("Invalid number of arguments");
-        }
-        match args[0] {
-            "a" => self.hlf(RegisterId::A),
-            "b" => self.hlf(RegisterId::B),
-            _ => panic!("Invalid register name {:?}", args),
-        }
+
+    pub fn a(&self) -> i64 {
+        self.a.val
     }
-    
-    /// Parse inc instruction
-    fn read_inc(&mut self, args: &[&str]) {
-        if args.len() != 1 {
-            panic!("Invalid number of arguments");
-        }
-        match args[0] {
-            "a" => self.inc(RegisterId::A),
-            "b" => self.inc(RegisterId::B),
-            _ => panic!("Invalid register name {:?}", args),
-        }
+
+    pub fn b(&self) -> i64 {
+        self.b.val
     }
-    
-    /// Parse inc instruction
-    fn read_tpl(&mut self, args: &[&str]) {
-        if args.len() != 1 {
-            panic!("Invalid number of arguments");
-        }
-        match args[0] {
-            "a" => self.tpl(RegisterId::A),
-            "b" => self.tpl(RegisterId::B),
-            _ => panic!("Invalid register name {:?}", args),
+
+    /// Current value of the given register.
+    pub fn register(&self, r: RegisterId) -> i64 {
+        self.get_register_ref(r).val
+    }
+
+    /// Overwrite the given register, e.g. to deliver a value received over `rcv`.
+    pub fn set_register(&mut self, r: RegisterId, val: i64) {
+        self.get_register(r).val = val;
+    }
+
+    /// The decoded instruction at the current PC, if the program hasn't run off the ROM.
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.rom.get(self.pc).cloned()
+    }
+
+    /// Move past the current instruction without otherwise acting on it.
+    pub fn advance(&mut self) {
+        self.pc += 1;
+    }
+
+    /// Run until the program halts naturally or a previously-seen (pc, registers)
+    /// state repeats. Tracking the full register snapshot alongside the PC, rather
+    /// than the PC alone, means a loop that makes progress each time around (e.g. a
+    /// `dec`/`jnz` countdown) is allowed to run to completion instead of being
+    /// mistaken for a cycle on its first repeated PC.
+    pub fn run(&mut self) -> Exit {
+        self.state = State::Running;
+        let mut visited = HashSet::new();
+
+        loop {
+            if self.pc >= self.rom.len() {
+                self.state = State::Halted;
+                return Exit::Halted;
+            }
+            if !visited.insert(self.snapshot()) {
+                self.state = State::Halted;
+                return Exit::LoopDetected { at: self.pc };
+            }
+            if let Err(fault) = self.step() {
+                self.state = State::Halted;
+                return Exit::Faulted(fault);
+            }
         }
     }
 
-    /// Parse jmp instruction
-    fn read_jmp(&mut self, args: &[&str]) {
-        if args.len() != 1 {
-            panic!("Invalid number of arguments");
+    /// The (pc, registers) state used to detect a repeating, non-terminating cycle.
+    fn snapshot(&self) -> (usize, i64, i64, i64) {
+        (self.pc, self.a.val, self.b.val, self.p.val)
+    }
+
+    /// Execute the next instruction. A PC that has run off the end of the ROM
+    /// is treated as a clean halt rather than an error.
+    pub fn step(&mut self) -> Result<(), Fault> {
+        // Decoded instructions are cheap to clone (no heap data), which lets
+        // us drop the borrow on `self.rom` before dispatching into `self`.
+        let instruction = match self.rom.get(self.pc) {
+            Some(instruction) => instruction.clone(),
+            None => {
+                self.state = State::Halted;
+                return Ok(());
+            }
+        };
+
+        if let Some(ref mut profile) = self.profile {
+            profile.record(Cpu::opcode_name(&instruction), self.pc);
         }
-        let (sign, offset_str) = args[0].split_at(1);
-        if sign != "+" {
-            panic!("Unexpected sign {}", sign);
+
+        match instruction {
+            Instruction::Hlf(r) => self.hlf(r),
+            Instruction::Tpl(r) => self.tpl(r),
+            Instruction::Inc(r) => self.inc(r),
+            Instruction::Dec(r) => self.dec(r),
+            Instruction::Cpy(src, dst) => self.cpy(src, dst),
+            Instruction::Jmp(offset) => self.jmp(offset)?,
+            Instruction::Jie(r, offset) => self.jie(r, offset)?,
+            Instruction::Jio(r, offset) => self.jio(r, offset)?,
+            Instruction::Jnz(test, offset) => self.jnz(test, offset)?,
+            Instruction::Snd(_) => return Err(Fault::UnsupportedHere("snd".to_string())),
+            Instruction::Rcv(_) => return Err(Fault::UnsupportedHere("rcv".to_string())),
+            Instruction::Ld(dst, addr) => self.ld(dst, addr)?,
+            Instruction::St(addr, src) => self.st(addr, src)?,
+            Instruction::Push(r) => self.push(r)?,
+            Instruction::Pop(r) => self.pop(r)?,
+            Instruction::Call(offset) => self.call(offset)?,
+            Instruction::Ret => self.ret()?,
         }
-        let offset = usize::from_str(offset_str).unwrap();
-        self.jmp(offset);
+        Ok(())
     }
 
-    /// Parse jio instruction
-    fn read_jio(&mut self, args: &[&str]) {
-        if args.len() != 2 {
-            panic!("Invalid number of arguments");
-        };
-        
-        {
-            let register = match args[0] {
-                "a," => &self.a,
-                "b," => &self.b,
-                _ => panic!("Invalid register name {:?}", args),
-            };
-            
-            if !register.is_odd() {
-                self.pc += 1;
-                return;
-            }
+    /// Short opcode name used by the profiler.
+    fn opcode_name(instruction: &Instruction) -> &'static str {
+        match *instruction {
+            Instruction::Hlf(_) => "hlf",
+            Instruction::Tpl(_) => "tpl",
+            Instruction::Inc(_) => "inc",
+            Instruction::Dec(_) => "dec",
+            Instruction::Cpy(..) => "cpy",
+            Instruction::Jmp(_) => "jmp",
+            Instruction::Jie(..) => "jie",
+            Instruction::Jio(..) => "jio",
+            Instruction::Jnz(..) => "jnz",
+            Instruction::Snd(_) => "snd",
+            Instruction::Rcv(_) => "rcv",
+            Instruction::Ld(..) => "ld",
+            Instruction::St(..) => "st",
+            Instruction::Push(_) => "push",
+            Instruction::Pop(_) => "pop",
+            Instruction::Call(_) => "call",
+            Instruction::Ret => "ret",
         }
-        
-        // TODO: Avoid duplicate code
-        let (sign, offset_str) = args[1].split_at(1);
-        if sign != "+" {
-            panic!("Unexpected sign {}", sign);
+    }
+
+    /// Resolve an operand to its current value, reading the register file for `Operand::Reg`.
+    fn operand_value(&self, op: Operand) -> i64 {
+        match op {
+            Operand::Reg(r) => self.get_register_ref(r).val,
+            Operand::Imm(v) => v,
         }
-        let offset = usize::from_str(offset_str).unwrap();
-        self.jmp(offset);
     }
-    
+
     fn get_register(&mut self, r: RegisterId) -> &mut Register {
         match r {
             RegisterId::A => &mut self.a,
             RegisterId::B => &mut self.b,
+            RegisterId::P => &mut self.p,
+        }
+    }
+
+    fn get_register_ref(&self, r: RegisterId) -> &Register {
+        match r {
+            RegisterId::A => &self.a,
+            RegisterId::B => &self.b,
+            RegisterId::P => &self.p,
         }
     }
 
@@ -192,27 +338,152 @@ impl Cpu {
         self.pc += 1;
     }
 
+    /// dec r decrements register r, subtracting 1, then continues with the next instruction.
+    fn dec(&mut self, rid: RegisterId) {
+        {
+            let register = self.get_register(rid);
+            *register -= 1;
+        }
+        self.pc += 1;
+    }
+
+    /// cpy x y copies x (either a register or a literal) into register y.
+    fn cpy(&mut self, src: Operand, dst: RegisterId) {
+        let val = self.operand_value(src);
+        {
+            let register = self.get_register(dst);
+            register.val = val;
+        }
+        self.pc += 1;
+    }
+
     /// jmp offset is a jump; it continues with the instruction offset away relative to itself.
-    fn jmp(&mut self, offset: usize) {
-        self.pc += offset;
+    /// A negative result is rejected outright rather than silently wrapping to a huge `usize`;
+    /// jumping past the end of the ROM is still allowed, since that's how `run()` detects a
+    /// natural halt.
+    fn jmp(&mut self, offset: isize) -> Result<(), Fault> {
+        let target = self.pc as isize + offset;
+        if target < 0 {
+            return Err(Fault::PcOutOfBounds(self.pc));
+        }
+        self.pc = target as usize;
+        Ok(())
+    }
+
+    /// jnz x offset jumps by offset if x is non-zero, otherwise continues with the next instruction.
+    fn jnz(&mut self, test: Operand, offset: isize) -> Result<(), Fault> {
+        if self.operand_value(test) != 0 {
+            self.jmp(offset)
+        } else {
+            self.pc += 1;
+            Ok(())
+        }
+    }
+
+    /// jie r, offset is like jmp, but only jumps if register r is even ("jump if even").
+    fn jie(&mut self, rid: RegisterId, offset: isize) -> Result<(), Fault> {
+        if self.get_register_ref(rid).is_even() {
+            self.jmp(offset)
+        } else {
+            self.pc += 1;
+            Ok(())
+        }
+    }
+
+    /// jio r, offset is like jmp, but only jumps if register r is 1 ("jump if one", not odd).
+    fn jio(&mut self, rid: RegisterId, offset: isize) -> Result<(), Fault> {
+        if self.get_register_ref(rid).val == 1 {
+            self.jmp(offset)
+        } else {
+            self.pc += 1;
+            Ok(())
+        }
+    }
+
+    /// ld r addr loads the value at memory address addr into register r.
+    fn ld(&mut self, rid: RegisterId, addr: Operand) -> Result<(), Fault> {
+        let index = self.memory_index(addr)?;
+        let val = self.memory[index];
+        self.get_register(rid).val = val;
+        self.pc += 1;
+        Ok(())
     }
 
-    // jie r, offset is like jmp, but only jumps if register r is even ("jump if even").
+    /// st addr r stores the value of register r into memory address addr.
+    fn st(&mut self, addr: Operand, rid: RegisterId) -> Result<(), Fault> {
+        let index = self.memory_index(addr)?;
+        let val = self.get_register_ref(rid).val;
+        self.memory[index] = val;
+        self.pc += 1;
+        Ok(())
+    }
 
+    /// Resolve an address operand to a valid index into `memory`.
+    fn memory_index(&self, addr: Operand) -> Result<usize, Fault> {
+        let addr = self.operand_value(addr);
+        if addr < 0 || addr as usize >= self.memory.len() {
+            return Err(Fault::MemoryOutOfBounds(addr.max(0) as usize));
+        }
+        Ok(addr as usize)
+    }
+
+    /// push r pushes the value of register r onto the stack.
+    fn push(&mut self, rid: RegisterId) -> Result<(), Fault> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(Fault::StackOverflow);
+        }
+        let val = self.get_register_ref(rid).val;
+        self.stack.push(val);
+        self.pc += 1;
+        Ok(())
+    }
+
+    /// pop r pops the top of the stack into register r.
+    fn pop(&mut self, rid: RegisterId) -> Result<(), Fault> {
+        let val = self.stack.pop().ok_or(Fault::StackUnderflow)?;
+        self.get_register(rid).val = val;
+        self.pc += 1;
+        Ok(())
+    }
+
+    /// call offset pushes the return address and jumps by offset, like a subroutine call.
+    fn call(&mut self, offset: isize) -> Result<(), Fault> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(Fault::StackOverflow);
+        }
+        self.stack.push((self.pc + 1) as i64);
+        if let Err(fault) = self.jmp(offset) {
+            // Don't leave a bogus return address behind for a later `ret` to pick up.
+            self.stack.pop();
+            return Err(fault);
+        }
+        Ok(())
+    }
+
+    /// ret pops a return address pushed by call and resumes execution there.
+    fn ret(&mut self) -> Result<(), Fault> {
+        let addr = self.stack.pop().ok_or(Fault::StackUnderflow)?;
+        if addr < 0 {
+            return Err(Fault::PcOutOfBounds(0));
+        }
+        self.pc = addr as usize;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::RegisterId;
     use super::Cpu;
-    use rom::Rom;
+    use fault::Fault;
+    use rom::{Operand, Rom};
 
     #[test]
     fn test_hlf() {
         let tests = [(10, 0), (7, 0), (0, 4)];
         let results = [(5, 0), (3, 0), (0, 2)];
         for (&(a, b), &(ar, br)) in tests.iter().zip(results.iter()) {
-            let mut cpu = Cpu::new(Rom::new(vec![]));
+            let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
             cpu.a += a;
             cpu.b += b;
             cpu.hlf(RegisterId::A);
@@ -227,7 +498,7 @@ mod tests {
         let tests = [(10, 0), (0, 0)];
         let results = [(30, 0), (0, 0)];
         for (&(a, b), &(ar, br)) in tests.iter().zip(results.iter()) {
-            let mut cpu = Cpu::new(Rom::new(vec![]));
+            let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
             cpu.a += a;
             cpu.b += b;
             cpu.tpl(RegisterId::A);
@@ -242,7 +513,7 @@ mod tests {
         let tests = [(1, 0), (1, 1)];
         let results = [(2, 1), (2, 2)];
         for (&(a, b), &(ar, br)) in tests.iter().zip(results.iter()) {
-            let mut cpu = Cpu::new(Rom::new(vec![]));
+            let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
             cpu.a += a;
             cpu.b += b;
             cpu.inc(RegisterId::A);
@@ -251,4 +522,228 @@ mod tests {
             assert_eq!(br, cpu.b.val);
         }
     }
+
+    #[test]
+    fn test_jie() {
+        // jie jumps on an even register value, and otherwise just advances.
+        let tests = [(0, 5), (2, 5), (1, 5), (-3, 5)];
+        let results = [5, 5, 1, 1];
+        for (&(a, offset), &pc) in tests.iter().zip(results.iter()) {
+            let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+            cpu.a += a;
+            cpu.jie(RegisterId::A, offset).unwrap();
+            assert_eq!(pc, cpu.pc);
+        }
+    }
+
+    #[test]
+    fn test_jio() {
+        // jio jumps only when the register is exactly 1, not on any odd value.
+        let tests = [(1, 5), (0, 5), (3, 5), (-1, 5)];
+        let results = [5, 1, 1, 1];
+        for (&(a, offset), &pc) in tests.iter().zip(results.iter()) {
+            let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+            cpu.a += a;
+            cpu.jio(RegisterId::A, offset).unwrap();
+            assert_eq!(pc, cpu.pc);
+        }
+    }
+
+    #[test]
+    fn test_cpy() {
+        // cpy accepts both a literal and a register as its source.
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.cpy(Operand::Imm(7), RegisterId::A);
+        assert_eq!(7, cpu.a.val);
+
+        cpu.cpy(Operand::Reg(RegisterId::A), RegisterId::B);
+        assert_eq!(7, cpu.b.val);
+    }
+
+    #[test]
+    fn test_dec() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.a += 3;
+        cpu.dec(RegisterId::A);
+        cpu.dec(RegisterId::A);
+        assert_eq!(1, cpu.a.val);
+    }
+
+    #[test]
+    fn test_jnz() {
+        // jnz jumps by the offset unless the operand is zero.
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.jnz(Operand::Imm(0), 5).unwrap();
+        assert_eq!(1, cpu.pc);
+
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.jnz(Operand::Imm(2), 5).unwrap();
+        assert_eq!(5, cpu.pc);
+    }
+
+    #[test]
+    fn test_jmp_accepts_negative_offsets() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.pc = 10;
+        cpu.jmp(-3).unwrap();
+        assert_eq!(7, cpu.pc);
+    }
+
+    #[test]
+    fn test_jmp_rejects_negative_pc() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.pc = 2;
+        match cpu.jmp(-5) {
+            Err(Fault::PcOutOfBounds(2)) => {}
+            other => panic!("expected PcOutOfBounds(2), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_halts_when_pc_runs_off_the_rom() {
+        let rom = Rom::new(vec!["inc a".to_string()]).unwrap();
+        let mut cpu = Cpu::new(rom);
+        assert_eq!(super::Exit::Halted, cpu.run());
+        assert_eq!(1, cpu.a());
+    }
+
+    #[test]
+    fn test_run_detects_a_loop() {
+        let rom = Rom::new(vec!["jmp +0".to_string()]).unwrap();
+        let mut cpu = Cpu::new(rom);
+        assert_eq!(super::Exit::LoopDetected { at: 0 }, cpu.run());
+    }
+
+    #[test]
+    fn test_run_lets_a_terminating_loop_run_to_completion() {
+        // A dec/jnz countdown revisits the same PCs, but never the same register
+        // state twice, so it must halt rather than being flagged as a loop.
+        let rom = Rom::new(vec!["cpy 5 a".to_string(), "dec a".to_string(), "jnz a -1".to_string()])
+            .unwrap();
+        let mut cpu = Cpu::new(rom);
+        assert_eq!(super::Exit::Halted, cpu.run());
+        assert_eq!(0, cpu.a());
+    }
+
+    #[test]
+    fn test_snd_rcv_are_unsupported_outside_duet() {
+        let rom = Rom::new(vec!["snd a".to_string()]).unwrap();
+        let mut cpu = Cpu::new(rom);
+        match cpu.step() {
+            Err(Fault::UnsupportedHere(ref op)) if op == "snd" => {}
+            other => panic!("expected UnsupportedHere(\"snd\"), got {:?}", other),
+        }
+
+        let rom = Rom::new(vec!["rcv a".to_string()]).unwrap();
+        let mut cpu = Cpu::new(rom);
+        match cpu.step() {
+            Err(Fault::UnsupportedHere(ref op)) if op == "rcv" => {}
+            other => panic!("expected UnsupportedHere(\"rcv\"), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ld_st_reject_out_of_range_addresses() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        match cpu.ld(RegisterId::A, Operand::Imm(super::MEMORY_SIZE as i64)) {
+            Err(Fault::MemoryOutOfBounds(_)) => {}
+            other => panic!("expected MemoryOutOfBounds, got {:?}", other),
+        }
+        match cpu.st(Operand::Imm(-1), RegisterId::A) {
+            Err(Fault::MemoryOutOfBounds(_)) => {}
+            other => panic!("expected MemoryOutOfBounds, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pop_and_ret_underflow_an_empty_stack() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        assert_eq!(Err(Fault::StackUnderflow), cpu.pop(RegisterId::A));
+
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        assert_eq!(Err(Fault::StackUnderflow), cpu.ret());
+    }
+
+    #[test]
+    fn test_push_and_call_overflow_a_full_stack() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        for _ in 0..super::STACK_SIZE {
+            cpu.push(RegisterId::A).unwrap();
+        }
+        assert_eq!(Err(Fault::StackOverflow), cpu.push(RegisterId::A));
+        assert_eq!(Err(Fault::StackOverflow), cpu.call(1));
+    }
+
+    #[test]
+    fn test_ret_rejects_a_negative_return_address() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.stack.push(-1);
+        match cpu.ret() {
+            Err(Fault::PcOutOfBounds(_)) => {}
+            other => panic!("expected PcOutOfBounds, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_leaves_the_stack_untouched_when_the_jump_fails() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.pc = 2;
+        match cpu.call(-5) {
+            Err(Fault::PcOutOfBounds(_)) => {}
+            other => panic!("expected PcOutOfBounds, got {:?}", other),
+        }
+        assert_eq!(0, cpu.sp());
+    }
+
+    #[test]
+    fn test_ld_st_round_trip_through_memory() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.a += 42;
+        cpu.st(Operand::Imm(3), RegisterId::A).unwrap();
+        cpu.ld(RegisterId::B, Operand::Imm(3)).unwrap();
+        assert_eq!(42, cpu.b.val);
+    }
+
+    #[test]
+    fn test_push_pop_round_trip_through_the_stack() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.a += 9;
+        cpu.push(RegisterId::A).unwrap();
+        cpu.pop(RegisterId::B).unwrap();
+        assert_eq!(9, cpu.b.val);
+        assert_eq!(0, cpu.sp());
+    }
+
+    #[test]
+    fn test_call_ret_round_trip_through_the_program_counter() {
+        let mut cpu = Cpu::new(Rom::new(vec![]).unwrap());
+        cpu.pc = 2;
+        cpu.call(10).unwrap();
+        assert_eq!(12, cpu.pc);
+
+        cpu.ret().unwrap();
+        assert_eq!(3, cpu.pc);
+    }
+
+    #[test]
+    fn test_profile_report_is_none_until_enabled() {
+        let rom = Rom::new(vec!["inc a".to_string()]).unwrap();
+        let mut cpu = Cpu::new(rom);
+        cpu.run();
+        assert_eq!(None, cpu.profile_report(5));
+    }
+
+    #[test]
+    fn test_profile_report_counts_opcodes_and_pcs() {
+        let rom = Rom::new(vec!["inc a".to_string(), "inc a".to_string(), "inc b".to_string()])
+            .unwrap();
+        let mut cpu = Cpu::new(rom);
+        cpu.enable_profiling();
+        cpu.run();
+
+        let report = cpu.profile_report(1).unwrap();
+        assert_eq!(vec![("inc", 3)], report.opcode_counts);
+        assert_eq!(1, report.hot_pcs.len());
+        assert_eq!(1, (report.hot_pcs[0].1));
+    }
 }
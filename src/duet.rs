@@ -0,0 +1,163 @@
+use std::collections::{HashSet, VecDeque};
+
+use cpu::{Cpu, RegisterId};
+use fault::Fault;
+use rom::{Instruction, Operand, Rom};
+
+/// Why `run_duet` stopped scheduling the two programs.
+#[derive(Debug, PartialEq)]
+pub enum DuetExit {
+    /// Neither program can run further and at least one ran off the end of its ROM;
+    /// the other may be left blocked on an empty `rcv`.
+    Halted,
+    /// Both programs are blocked on an empty `rcv` and neither can ever unblock the other.
+    Deadlocked,
+    /// A program revisited the same (pc, registers) state within one scheduling turn
+    /// with no successful `rcv` in between, so it would spin forever without
+    /// blocking or halting.
+    LoopDetected { program: usize, at: usize },
+}
+
+/// Outcome of running two communicating programs to completion, deadlock, or a spin.
+#[derive(Debug, PartialEq)]
+pub struct DuetReport {
+    /// Number of `snd` instructions each program executed, indexed by program id.
+    pub sent: [usize; 2],
+    pub exit: DuetExit,
+}
+
+/// Run two copies of a program concurrently as programs 0 and 1, wired
+/// together by `snd`/`rcv` message passing, until neither can run further
+/// (each is halted or blocked on an empty `rcv`; a deadlock when both are
+/// blocked), or one spins forever without blocking or halting.
+pub fn run_duet(rom0: Rom, rom1: Rom) -> Result<DuetReport, Fault> {
+    let mut cpus = [Cpu::with_program_id(rom0, 0), Cpu::with_program_id(rom1, 1)];
+    let mut queues = [VecDeque::new(), VecDeque::new()];
+    let mut sent = [0usize; 2];
+    let mut blocked = [false; 2];
+
+    loop {
+        let mut progressed = false;
+
+        for i in 0..2 {
+            let other = 1 - i;
+            let mut visited = HashSet::new();
+            blocked[i] = false;
+
+            loop {
+                let instruction = match cpus[i].current_instruction() {
+                    Some(instruction) => instruction,
+                    None => break, // halted
+                };
+
+                if !visited.insert(snapshot(&cpus[i])) {
+                    return Ok(DuetReport {
+                        sent: sent,
+                        exit: DuetExit::LoopDetected { program: i, at: cpus[i].pc() },
+                    });
+                }
+
+                match instruction {
+                    Instruction::Snd(operand) => {
+                        let value = operand_value(&cpus[i], operand);
+                        queues[other].push_back(value);
+                        sent[i] += 1;
+                        cpus[i].advance();
+                    }
+                    Instruction::Rcv(register) => {
+                        match queues[i].pop_front() {
+                            Some(value) => {
+                                cpus[i].set_register(register, value);
+                                cpus[i].advance();
+                                // New input: a repeated state after this is not a spin.
+                                visited.clear();
+                            }
+                            None => {
+                                blocked[i] = true;
+                                break; // blocked on an empty queue
+                            }
+                        }
+                    }
+                    _ => cpus[i].step()?,
+                }
+
+                progressed = true;
+            }
+        }
+
+        if !progressed {
+            let exit = if blocked[0] && blocked[1] {
+                DuetExit::Deadlocked
+            } else {
+                DuetExit::Halted
+            };
+            return Ok(DuetReport { sent: sent, exit: exit });
+        }
+    }
+}
+
+fn operand_value(cpu: &Cpu, operand: Operand) -> i64 {
+    match operand {
+        Operand::Reg(r) => cpu.register(r),
+        Operand::Imm(v) => v,
+    }
+}
+
+/// The (pc, registers) state used to detect a repeating, non-terminating spin
+/// within a single scheduling turn. Mirrors `Cpu::run()`'s loop detection, so a
+/// bounded counting loop that makes progress each time around isn't mistaken
+/// for a cycle on its first repeated PC.
+fn snapshot(cpu: &Cpu) -> (usize, i64, i64, i64) {
+    (cpu.pc(), cpu.register(RegisterId::A), cpu.register(RegisterId::B), cpu.register(RegisterId::P))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_duet, DuetExit};
+    use rom::Rom;
+
+    #[test]
+    fn test_run_duet_exchanges_messages_and_halts() {
+        let program = vec!["snd p".to_string(), "snd p".to_string()];
+        let rom0 = Rom::new(program.clone()).unwrap();
+        let rom1 = Rom::new(program).unwrap();
+
+        let report = run_duet(rom0, rom1).unwrap();
+        assert_eq!(DuetExit::Halted, report.exit);
+        assert_eq!([2, 2], report.sent);
+    }
+
+    #[test]
+    fn test_run_duet_detects_deadlock() {
+        let program = vec!["rcv a".to_string()];
+        let rom0 = Rom::new(program.clone()).unwrap();
+        let rom1 = Rom::new(program).unwrap();
+
+        let report = run_duet(rom0, rom1).unwrap();
+        assert_eq!(DuetExit::Deadlocked, report.exit);
+        assert_eq!([0, 0], report.sent);
+    }
+
+    #[test]
+    fn test_run_duet_detects_a_spinning_program() {
+        let rom0 = Rom::new(vec!["jmp +0".to_string()]).unwrap();
+        let rom1 = Rom::new(vec!["rcv a".to_string()]).unwrap();
+
+        let report = run_duet(rom0, rom1).unwrap();
+        assert_eq!(DuetExit::LoopDetected { program: 0, at: 0 }, report.exit);
+    }
+
+    #[test]
+    fn test_run_duet_lets_a_bounded_loop_run_to_completion() {
+        // A dec/jnz countdown before the snd revisits the same PCs, but never the
+        // same register state twice, so it must not be mistaken for a spin.
+        let program = vec!["cpy 3 a".to_string(), "dec a".to_string(), "jnz a -1".to_string(),
+                           "snd a".to_string()];
+        let rom0 = Rom::new(program.clone()).unwrap();
+        let rom1 = Rom::new(program).unwrap();
+
+        let report = run_duet(rom0, rom1).unwrap();
+        assert_eq!(DuetExit::Halted, report.exit);
+        assert_eq!([1, 1], report.sent);
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+
+/// Something went wrong decoding or executing a program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    UnknownOpcode(String),
+    BadArgs { op: String, got: usize },
+    BadRegister(String),
+    BadOffset(String),
+    PcOutOfBounds(usize),
+    /// Instruction needs a context `step` doesn't have, e.g. `snd`/`rcv` run
+    /// outside the duet scheduler.
+    UnsupportedHere(String),
+    MemoryOutOfBounds(usize),
+    StackUnderflow,
+    StackOverflow,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::UnknownOpcode(ref op) => write!(f, "unimplemented or illegal instruction: {}", op),
+            Fault::BadArgs { ref op, got } => {
+                write!(f, "invalid number of arguments for {}: got {}", op, got)
+            }
+            Fault::BadRegister(ref name) => write!(f, "invalid register name: {}", name),
+            Fault::BadOffset(ref s) => write!(f, "invalid offset: {}", s),
+            Fault::PcOutOfBounds(pc) => write!(f, "program counter out of bounds: {}", pc),
+            Fault::UnsupportedHere(ref op) => write!(f, "{} requires the duet scheduler", op),
+            Fault::MemoryOutOfBounds(addr) => write!(f, "memory access out of bounds: {}", addr),
+            Fault::StackUnderflow => write!(f, "stack underflow"),
+            Fault::StackOverflow => write!(f, "stack overflow"),
+        }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod cpu;
+pub mod duet;
+pub mod fault;
 pub mod rom;
 
 use std::io::prelude::*;
@@ -19,11 +21,9 @@ fn lines_from_file<P>(filename: P) -> Vec<String>
 
 fn main() {
     let lines = lines_from_file("roms/big.rom");
-    let rom = Rom::new(lines);
+    let rom = Rom::new(lines).expect("Could not assemble ROM");
     let mut cpu = Cpu::new(rom);
 
-    //loop {
-    for i in 1..10 {
-        cpu.step()
-    }
+    let exit = cpu.run();
+    println!("{:?}: a = {}, b = {}", exit, cpu.a(), cpu.b());
 }
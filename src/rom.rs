@@ -1,28 +1,242 @@
-use std::ops::Index;
+use std::str::FromStr;
 
+use cpu::RegisterId;
+use fault::Fault;
+
+/// An instruction argument that is either a register or a literal value.
+#[derive(Debug, Clone, Copy)]
+pub enum Operand {
+    Reg(RegisterId),
+    Imm(i64),
+}
+
+/// A single decoded instruction, ready for direct dispatch.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Hlf(RegisterId),
+    Tpl(RegisterId),
+    Inc(RegisterId),
+    Dec(RegisterId),
+    Cpy(Operand, RegisterId),
+    Jmp(isize),
+    Jie(RegisterId, isize),
+    Jio(RegisterId, isize),
+    Jnz(Operand, isize),
+    Snd(Operand),
+    Rcv(RegisterId),
+    Ld(RegisterId, Operand),
+    St(Operand, RegisterId),
+    Push(RegisterId),
+    Pop(RegisterId),
+    Call(isize),
+    Ret,
+}
+
+#[derive(Debug)]
 pub struct Rom {
-    instructions: Vec<String>,
+    instructions: Vec<Instruction>,
 }
 
 impl Rom {
-    pub fn new(instructions: Vec<String>) -> Rom {
-        Rom { instructions: instructions }
+    /// Assemble raw source lines into a decoded instruction vector.
+    pub fn new(lines: Vec<String>) -> Result<Rom, Fault> {
+        let instructions = lines.iter()
+            .map(|line| Rom::decode(line))
+            .collect::<Result<Vec<Instruction>, Fault>>()?;
+        Ok(Rom { instructions: instructions })
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Instruction> {
+        self.instructions.get(index)
+    }
+
+    /// Parse a single source line into an `Instruction`.
+    fn decode(line: &str) -> Result<Instruction, Fault> {
+        let tokens: Vec<&str> = line.split(' ').collect();
+        let (opcode, args) = tokens.split_at(1);
+
+        match opcode[0] {
+            "hlf" => Ok(Instruction::Hlf(Rom::read_register(opcode[0], args)?)),
+            "tpl" => Ok(Instruction::Tpl(Rom::read_register(opcode[0], args)?)),
+            "inc" => Ok(Instruction::Inc(Rom::read_register(opcode[0], args)?)),
+            "dec" => Ok(Instruction::Dec(Rom::read_register(opcode[0], args)?)),
+            "cpy" => Rom::read_cpy(args),
+            "jmp" => Ok(Instruction::Jmp(Rom::read_offset(opcode[0], args)?)),
+            "jie" => Rom::read_jie(args),
+            "jio" => Rom::read_jio(args),
+            "jnz" => Rom::read_jnz(args),
+            "snd" => Rom::read_snd(args),
+            "rcv" => Ok(Instruction::Rcv(Rom::read_register(opcode[0], args)?)),
+            "ld" => Rom::read_ld(args),
+            "st" => Rom::read_st(args),
+            "push" => Ok(Instruction::Push(Rom::read_register(opcode[0], args)?)),
+            "pop" => Ok(Instruction::Pop(Rom::read_register(opcode[0], args)?)),
+            "call" => Ok(Instruction::Call(Rom::read_offset(opcode[0], args)?)),
+            "ret" => {
+                if !args.is_empty() {
+                    return Err(Fault::BadArgs { op: "ret".to_string(), got: args.len() });
+                }
+                Ok(Instruction::Ret)
+            }
+            op => Err(Fault::UnknownOpcode(op.to_string())),
+        }
+    }
+
+    /// Parse a single register argument, e.g. for `hlf`/`tpl`/`inc`/`dec`.
+    fn read_register(op: &str, args: &[&str]) -> Result<RegisterId, Fault> {
+        if args.len() != 1 {
+            return Err(Fault::BadArgs { op: op.to_string(), got: args.len() });
+        }
+        Rom::register_from_str(args[0])
+    }
+
+    /// Parse a single signed offset argument, e.g. for `jmp`.
+    fn read_offset(op: &str, args: &[&str]) -> Result<isize, Fault> {
+        if args.len() != 1 {
+            return Err(Fault::BadArgs { op: op.to_string(), got: args.len() });
+        }
+        Rom::offset_from_str(args[0])
+    }
+
+    /// Parse a `jie r, offset` instruction.
+    fn read_jie(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 2 {
+            return Err(Fault::BadArgs { op: "jie".to_string(), got: args.len() });
+        }
+        let register = Rom::register_from_str(args[0].trim_end_matches(','))?;
+        let offset = Rom::offset_from_str(args[1])?;
+        Ok(Instruction::Jie(register, offset))
+    }
+
+    /// Parse a `jio r, offset` instruction.
+    fn read_jio(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 2 {
+            return Err(Fault::BadArgs { op: "jio".to_string(), got: args.len() });
+        }
+        let register = Rom::register_from_str(args[0].trim_end_matches(','))?;
+        let offset = Rom::offset_from_str(args[1])?;
+        Ok(Instruction::Jio(register, offset))
+    }
+
+    /// Parse a `cpy operand reg` instruction.
+    fn read_cpy(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 2 {
+            return Err(Fault::BadArgs { op: "cpy".to_string(), got: args.len() });
+        }
+        let src = Rom::operand_from_str(args[0])?;
+        let dst = Rom::register_from_str(args[1])?;
+        Ok(Instruction::Cpy(src, dst))
     }
 
-    pub fn get(&mut self, index: usize) -> String {
-        self.instructions[index].clone()
+    /// Parse a `jnz operand offset` instruction.
+    fn read_jnz(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 2 {
+            return Err(Fault::BadArgs { op: "jnz".to_string(), got: args.len() });
+        }
+        let test = Rom::operand_from_str(args[0])?;
+        let offset = Rom::offset_from_str(args[1])?;
+        Ok(Instruction::Jnz(test, offset))
+    }
+
+    /// Parse a `snd operand` instruction.
+    fn read_snd(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 1 {
+            return Err(Fault::BadArgs { op: "snd".to_string(), got: args.len() });
+        }
+        let value = Rom::operand_from_str(args[0])?;
+        Ok(Instruction::Snd(value))
+    }
+
+    /// Parse a `ld reg addr` instruction.
+    fn read_ld(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 2 {
+            return Err(Fault::BadArgs { op: "ld".to_string(), got: args.len() });
+        }
+        let dst = Rom::register_from_str(args[0])?;
+        let addr = Rom::operand_from_str(args[1])?;
+        Ok(Instruction::Ld(dst, addr))
+    }
+
+    /// Parse a `st addr reg` instruction.
+    fn read_st(args: &[&str]) -> Result<Instruction, Fault> {
+        if args.len() != 2 {
+            return Err(Fault::BadArgs { op: "st".to_string(), got: args.len() });
+        }
+        let addr = Rom::operand_from_str(args[0])?;
+        let src = Rom::register_from_str(args[1])?;
+        Ok(Instruction::St(addr, src))
+    }
+
+    fn register_from_str(s: &str) -> Result<RegisterId, Fault> {
+        match s {
+            "a" => Ok(RegisterId::A),
+            "b" => Ok(RegisterId::B),
+            "p" => Ok(RegisterId::P),
+            _ => Err(Fault::BadRegister(s.to_string())),
+        }
+    }
+
+    fn offset_from_str(s: &str) -> Result<isize, Fault> {
+        if s.len() < 2 {
+            return Err(Fault::BadOffset(s.to_string()));
+        }
+        let (sign, digits) = s.split_at(1);
+        match sign {
+            "+" => isize::from_str(digits).map_err(|_| Fault::BadOffset(s.to_string())),
+            "-" => isize::from_str(digits).map(|n| -n).map_err(|_| Fault::BadOffset(s.to_string())),
+            _ => Err(Fault::BadOffset(s.to_string())),
+        }
+    }
+
+    /// Parse an argument as either a literal value or a register name.
+    fn operand_from_str(s: &str) -> Result<Operand, Fault> {
+        match s.chars().next() {
+            Some(c) if c.is_digit(10) || c == '-' => {
+                i64::from_str(s).map(Operand::Imm).map_err(|_| Fault::BadOffset(s.to_string()))
+            }
+            _ => Rom::register_from_str(s).map(Operand::Reg),
+        }
     }
 }
 
-// Make access to the ROM data more convenient
-// This does not work. I guess because the size of instructions[index]
-// is not known at compile time...
-// Maybe I get this to work later.
-// impl Index<usize> for Rom {
-//    type Output = str;
-//
-//    fn index<'a>(&'a self, index: usize) -> &'a str {
-//        &self.instructions[index][..]
-//    }
-// }
-//
+#[cfg(test)]
+mod tests {
+    use super::Rom;
+    use fault::Fault;
+
+    #[test]
+    fn test_unknown_opcode() {
+        match Rom::new(vec!["frob a".to_string()]) {
+            Err(Fault::UnknownOpcode(ref op)) if op == "frob" => {}
+            other => panic!("expected UnknownOpcode(\"frob\"), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bad_args() {
+        match Rom::new(vec!["inc a b".to_string()]) {
+            Err(Fault::BadArgs { ref op, got: 2 }) if op == "inc" => {}
+            other => panic!("expected BadArgs {{ op: \"inc\", got: 2 }}, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bad_register() {
+        match Rom::new(vec!["inc z".to_string()]) {
+            Err(Fault::BadRegister(ref name)) if name == "z" => {}
+            other => panic!("expected BadRegister(\"z\"), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bad_offset() {
+        match Rom::new(vec!["jmp 3".to_string()]) {
+            Err(Fault::BadOffset(ref s)) if s == "3" => {}
+            other => panic!("expected BadOffset(\"3\"), got {:?}", other),
+        }
+    }
+}